@@ -1,8 +1,11 @@
-use crate::io_source::IoSource;
-use crate::{event, sys, Interest, Registry, Token};
+mod readiness;
+
+use readiness::{Capacity, Readiness};
+
+use crate::{event, Interest, Registry, Token};
 use std::{
-    io,
-    sync::{mpsc, Arc, Mutex},
+    error, fmt, io,
+    sync::{mpsc, Arc},
 };
 
 /// Create a pair of the [`Sender`] and the [`Receiver`].
@@ -11,39 +14,148 @@ use std::{
 /// with the [`mio::poll::Poll`], while the [`Sender`] doesn't.
 pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
     let (tx, rx) = mpsc::channel();
+    let readiness = Arc::new(Readiness::new());
 
-    (Sender { inner: tx }, Receiver { inner: rx })
+    (
+        Sender {
+            inner: tx,
+            readiness: readiness.clone(),
+        },
+        Receiver {
+            inner: rx,
+            readiness,
+            capacity: None,
+        },
+    )
 }
 
 /// Create a pair of the [`SyncSender`] and the [`Receiver`].
 ///
 /// The [`Receiver`] implements the [`event::Source`] so that it can be registered
-/// with the [`mio::poll::Poll`], while the [`Sender`] doesn't.
+/// with the [`mio::poll::Poll`], and so does the [`SyncSender`]: registering
+/// it for [`Interest::WRITABLE`] reports when the channel has capacity
+/// again instead of requiring the caller to busy-loop on `try_send`.
 pub fn sync_channel<T>(bound: usize) -> (SyncSender<T>, Receiver<T>) {
     let (tx, rx) = mpsc::sync_channel(bound);
+    let readiness = Arc::new(Readiness::new());
+    let capacity = Arc::new(Capacity::new(bound));
 
-    (SyncSender { inner: tx }, Receiver { inner: rx })
+    (
+        SyncSender {
+            inner: tx,
+            readiness: readiness.clone(),
+            capacity: capacity.clone(),
+        },
+        Receiver {
+            inner: rx,
+            readiness,
+            capacity: Some(capacity),
+        },
+    )
 }
 
 pub struct Receiver<T> {
-    inner: IoSource<mpsc::Receiver<T>>,
+    inner: mpsc::Receiver<T>,
+    readiness: Arc<Readiness>,
+    /// `Some` only for a `Receiver` paired with a `SyncSender`, i.e. one
+    /// created through [`sync_channel`].
+    capacity: Option<Arc<Capacity>>,
 }
 
 impl<T> Receiver<T> {
     /// Try to receive a value. It works just like [`mpsc::Receiver::try_recv`].
     pub fn try_recv(&self) -> Result<T, mpsc::TryRecvError> {
-        self.inner.do_io(|inner| inner.try_recv())
+        let value = self.inner.try_recv()?;
+        self.readiness.ack_one();
+        if let Some(capacity) = &self.capacity {
+            capacity.release_many(1);
+        }
+        Ok(value)
+    }
+
+    /// Return an iterator that drains all currently-queued values without
+    /// blocking, the same way [`mpsc::Receiver::try_iter`] does.
+    ///
+    /// Unlike calling [`Receiver::try_recv`] in a loop, the readiness
+    /// bookkeeping (the pending counter, and the capacity counter for a
+    /// bounded channel) is only updated once, when the iterator is dropped,
+    /// amortizing it across the whole burst instead of paying it per value.
+    pub fn try_iter(&self) -> TryIter<'_, T> {
+        TryIter {
+            receiver: self,
+            drained: 0,
+        }
+    }
+
+    /// Drain up to `limit` currently-queued values into `buf`, returning how
+    /// many were appended.
+    ///
+    /// Like [`Receiver::try_iter`], this decrements the pending counter (and
+    /// the capacity counter for a bounded channel) in bulk, once, instead of
+    /// per value. The internal wakeup is not cleared; if values are still
+    /// queued afterwards it is re-armed, so the next poll reports them.
+    pub fn recv_many(&self, buf: &mut Vec<T>, limit: usize) -> usize {
+        let mut drained = 0;
+        while drained < limit {
+            match self.inner.try_recv() {
+                Ok(value) => {
+                    buf.push(value);
+                    drained += 1;
+                }
+                Err(_) => break,
+            }
+        }
+
+        if drained > 0 {
+            self.readiness.ack(drained);
+            if let Some(capacity) = &self.capacity {
+                capacity.release_many(drained);
+            }
+        }
+
+        drained
     }
 }
 
-impl event::Source for Receiver<T> {
+/// Iterator returned by [`Receiver::try_iter`].
+pub struct TryIter<'a, T> {
+    receiver: &'a Receiver<T>,
+    drained: usize,
+}
+
+impl<'a, T> Iterator for TryIter<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        match self.receiver.inner.try_recv() {
+            Ok(value) => {
+                self.drained += 1;
+                Some(value)
+            }
+            Err(_) => None,
+        }
+    }
+}
+
+impl<'a, T> Drop for TryIter<'a, T> {
+    fn drop(&mut self) {
+        if self.drained > 0 {
+            self.receiver.readiness.ack(self.drained);
+            if let Some(capacity) = &self.receiver.capacity {
+                capacity.release_many(self.drained);
+            }
+        }
+    }
+}
+
+impl<T> event::Source for Receiver<T> {
     fn register(
         &mut self,
         registry: &Registry,
         token: Token,
         interests: Interest,
     ) -> io::Result<()> {
-        self.inner.register(registry, token, interests)
+        self.readiness.register(registry, token, interests)
     }
 
     fn reregister(
@@ -52,49 +164,324 @@ impl event::Source for Receiver<T> {
         token: Token,
         interests: Interest,
     ) -> io::Result<()> {
-        self.inner.reregister(registry, token, interests)
+        self.readiness.reregister(registry, token, interests)
     }
 
-    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
-        self.inner.deregister(registry)
+    fn deregister(&mut self, _registry: &Registry) -> io::Result<()> {
+        self.readiness.deregister()
     }
 }
 
 pub struct Sender<T> {
-    inner: IoSource<mpsc::Sender<T>>,
+    inner: mpsc::Sender<T>,
+    readiness: Arc<Readiness>,
 }
 
-impl<T> SyncSender<T> {
-    /// Try to send a value. It works just like [`mpsc::SyncSender::send`].
-    /// After sending it, it's waking upthe [`mio::poll::Poll`].
-    ///
-    /// Note that it does not return any I/O error even if it occurs
-    /// when waking up the [`mio::poll::Poll`].
+impl<T> Sender<T> {
+    /// Send a value. It works just like [`mpsc::Sender::send`].
+    /// After sending it, it wakes up the [`mio::poll::Poll`].
     pub fn send(&self, t: T) -> Result<(), mpsc::SendError<T>> {
-        self.inner.do_io(|inner| inner.send())
+        self.inner.send(t)?;
+        self.readiness.notify();
+        Ok(())
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Sender<T> {
+        self.readiness.inc_senders();
+        Sender {
+            inner: self.inner.clone(),
+            readiness: self.readiness.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.readiness.dec_senders();
     }
 }
 
 pub struct SyncSender<T> {
-    inner: IoSource<mpsc::SyncSender<T>>,
+    inner: mpsc::SyncSender<T>,
+    readiness: Arc<Readiness>,
+    capacity: Arc<Capacity>,
 }
 
 impl<T> SyncSender<T> {
-    /// Try to send a value. It works just like [`mpsc::SyncSender::send`].
-    /// After sending it, it's waking upthe [`mio::poll::Poll`].
-    ///
-    /// Note that it does not return any I/O error even if it occurs
-    /// when waking up the [`mio::poll::Poll`].
+    /// Send a value, blocking if the channel is full. It works just like
+    /// [`mpsc::SyncSender::send`]. After sending it, it wakes up the
+    /// [`mio::poll::Poll`].
     pub fn send(&self, t: T) -> Result<(), mpsc::SendError<T>> {
-        self.inner.do_io(|inner| inner.send())
+        // Reserve the slot before sending: the receiver can observe and
+        // `recv` the value the instant `inner.send` returns, and its
+        // `release_many` assumes every occupied slot was already accounted
+        // for here. Reserving afterwards would let that release race ahead
+        // of us and underflow `occupied`.
+        self.capacity.reserve();
+        match self.inner.send(t) {
+            Ok(()) => {
+                self.readiness.notify();
+                Ok(())
+            }
+            Err(err) => {
+                self.capacity.release_many(1);
+                Err(err)
+            }
+        }
     }
 
-    /// Try to send a value. It works just like [`mpsc::SyncSender::send`].
-    /// After sending it, it's waking upthe [`mio::poll::Poll`].
-    ///
-    /// Note that it does not return any I/O error even if it occurs
-    /// when waking up the [`mio::poll::Poll`].
-    pub fn try_send(&self, t: T) -> Result<(), mpsc::SendError<T>> {
-        self.inner.do_io(|inner| inner.try_send())
+    /// Try to send a value without blocking. It works just like
+    /// [`mpsc::SyncSender::try_send`], but returns a [`TrySendError`] so the
+    /// caller can tell a momentarily full channel from a disconnected one
+    /// and recover the value in either case. After sending it, it wakes up
+    /// the [`mio::poll::Poll`]; a full channel does not poke the wakeup.
+    pub fn try_send(&self, t: T) -> Result<(), TrySendError<T>> {
+        // See `send` for why the slot is reserved before the value is
+        // actually handed to the channel.
+        self.capacity.reserve();
+        match self.inner.try_send(t) {
+            Ok(()) => {
+                self.readiness.notify();
+                Ok(())
+            }
+            Err(mpsc::TrySendError::Full(t)) => {
+                self.capacity.release_many(1);
+                Err(TrySendError::Full(t))
+            }
+            Err(mpsc::TrySendError::Disconnected(t)) => {
+                self.capacity.release_many(1);
+                Err(TrySendError::Disconnected(t))
+            }
+        }
+    }
+}
+
+impl<T> event::Source for SyncSender<T> {
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.capacity.register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.capacity.reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, _registry: &Registry) -> io::Result<()> {
+        self.capacity.deregister()
+    }
+}
+
+impl<T> Clone for SyncSender<T> {
+    fn clone(&self) -> SyncSender<T> {
+        self.readiness.inc_senders();
+        SyncSender {
+            inner: self.inner.clone(),
+            readiness: self.readiness.clone(),
+            capacity: self.capacity.clone(),
+        }
+    }
+}
+
+impl<T> Drop for SyncSender<T> {
+    fn drop(&mut self) {
+        self.readiness.dec_senders();
+    }
+}
+
+/// An error returned from [`SyncSender::try_send`].
+///
+/// Mirrors [`mpsc::TrySendError`], letting callers distinguish a momentarily
+/// full channel, from which the value can be recovered and retried, from a
+/// channel whose [`Receiver`] has gone away for good.
+pub enum TrySendError<T> {
+    /// The channel is currently full; the value is returned so it isn't lost.
+    Full(T),
+    /// The receiving half has disconnected; the value is returned so it isn't lost.
+    Disconnected(T),
+}
+
+impl<T> fmt::Debug for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrySendError::Full(..) => "Full(..)".fmt(f),
+            TrySendError::Disconnected(..) => "Disconnected(..)".fmt(f),
+        }
+    }
+}
+
+impl<T> fmt::Display for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrySendError::Full(..) => "full channel".fmt(f),
+            TrySendError::Disconnected(..) => "sending on a disconnected channel".fmt(f),
+        }
+    }
+}
+
+impl<T> error::Error for TrySendError<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Events, Poll};
+    use std::time::Duration;
+
+    const TOKEN: Token = Token(0);
+
+    fn poll_once(poll: &mut Poll, events: &mut Events) {
+        poll.poll(events, Some(Duration::from_millis(100))).unwrap();
+    }
+
+    fn is_readable(events: &Events) -> bool {
+        events.iter().any(|e| e.token() == TOKEN && e.is_readable())
+    }
+
+    #[test]
+    fn channel_readiness_fires_on_send_and_clears_after_drain() {
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(4);
+        let (tx, mut rx) = channel::<u32>();
+        poll.registry()
+            .register(&mut rx, TOKEN, Interest::READABLE)
+            .unwrap();
+
+        poll_once(&mut poll, &mut events);
+        assert!(!is_readable(&events));
+
+        // A burst of sends should still only need a single wakeup, since
+        // only the 0 -> 1 transition pokes the waker.
+        tx.send(1).unwrap();
+        tx.send(2).unwrap();
+        tx.send(3).unwrap();
+
+        events.clear();
+        poll_once(&mut poll, &mut events);
+        assert!(is_readable(&events));
+
+        assert_eq!(rx.try_recv().unwrap(), 1);
+        assert_eq!(rx.try_recv().unwrap(), 2);
+        assert_eq!(rx.try_recv().unwrap(), 3);
+
+        events.clear();
+        poll_once(&mut poll, &mut events);
+        assert!(!is_readable(&events));
+    }
+
+    #[test]
+    fn disconnect_before_register_still_arms_readiness() {
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(4);
+        let (tx, mut rx) = channel::<u32>();
+        drop(tx);
+
+        poll.registry()
+            .register(&mut rx, TOKEN, Interest::READABLE)
+            .unwrap();
+
+        poll_once(&mut poll, &mut events);
+        assert!(is_readable(&events));
+        assert_eq!(rx.try_recv(), Err(mpsc::TryRecvError::Disconnected));
+    }
+
+    fn is_writable(events: &Events) -> bool {
+        events.iter().any(|e| e.token() == TOKEN && e.is_writable())
+    }
+
+    #[test]
+    fn sync_sender_writable_tracks_capacity() {
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(4);
+        let (mut tx, rx) = sync_channel::<u32>(1);
+        poll.registry()
+            .register(&mut tx, TOKEN, Interest::WRITABLE)
+            .unwrap();
+
+        // The channel starts out empty, so it's immediately writable.
+        poll_once(&mut poll, &mut events);
+        assert!(is_writable(&events));
+
+        tx.send(1).unwrap();
+        events.clear();
+        poll_once(&mut poll, &mut events);
+        assert!(!is_writable(&events));
+
+        // Freeing the one slot should make it writable again.
+        assert_eq!(rx.try_recv().unwrap(), 1);
+        events.clear();
+        poll_once(&mut poll, &mut events);
+        assert!(is_writable(&events));
+    }
+
+    #[test]
+    fn partial_recv_many_rearms_readiness_for_the_rest_of_the_queue() {
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(4);
+        let (tx, mut rx) = channel::<u32>();
+        poll.registry()
+            .register(&mut rx, TOKEN, Interest::READABLE)
+            .unwrap();
+
+        for i in 0..3 {
+            tx.send(i).unwrap();
+        }
+
+        poll_once(&mut poll, &mut events);
+        assert!(is_readable(&events));
+
+        // Draining fewer messages than are queued must not strand the rest:
+        // the readable edge that woke us up is already consumed, so if we
+        // don't re-arm, the remaining messages are never delivered.
+        let mut buf = Vec::new();
+        assert_eq!(rx.recv_many(&mut buf, 1), 1);
+        assert_eq!(buf, [0]);
+
+        events.clear();
+        poll_once(&mut poll, &mut events);
+        assert!(is_readable(&events));
+
+        assert_eq!(rx.recv_many(&mut buf, 8), 2);
+        assert_eq!(buf, [0, 1, 2]);
+
+        events.clear();
+        poll_once(&mut poll, &mut events);
+        assert!(!is_readable(&events));
+    }
+
+    #[test]
+    fn try_iter_partial_take_rearms_readiness_for_the_rest_of_the_queue() {
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(4);
+        let (tx, mut rx) = channel::<u32>();
+        poll.registry()
+            .register(&mut rx, TOKEN, Interest::READABLE)
+            .unwrap();
+
+        for i in 0..3 {
+            tx.send(i).unwrap();
+        }
+
+        poll_once(&mut poll, &mut events);
+        assert!(is_readable(&events));
+
+        let taken: Vec<u32> = rx.try_iter().take(1).collect();
+        assert_eq!(taken, [0]);
+
+        events.clear();
+        poll_once(&mut poll, &mut events);
+        assert!(is_readable(&events));
+
+        let rest: Vec<u32> = rx.try_iter().collect();
+        assert_eq!(rest, [1, 2]);
     }
 }
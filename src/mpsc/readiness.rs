@@ -0,0 +1,257 @@
+use crate::{sys, Interest, Registry, Token};
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Readiness shared between a channel's [`Receiver`] and its senders.
+///
+/// An `mpsc` channel has no underlying file descriptor, so nothing would
+/// ever make [`Poll`] report it as readable. `Readiness` backs the channel
+/// with a real OS readiness primitive (the same one [`Waker`] uses) and a
+/// `pending` counter, so that a burst of sends coalesces into a single
+/// wakeup instead of one syscall per message.
+///
+/// [`Waker`] only ever delivers a one-shot edge wakeup; it has no way to
+/// drain or "un-signal" itself once armed. So rather than try to clear it
+/// after every drain, `Readiness` only ever calls `wake()`: on the `0 -> 1`
+/// pending transition, after a bulk drain that leaves values pending, when
+/// the last sender goes away, and on registration if values are already
+/// pending or the channel is already disconnected.
+///
+/// [`Receiver`]: crate::mpsc::Receiver
+/// [`Poll`]: crate::Poll
+/// [`Waker`]: crate::Waker
+#[derive(Debug)]
+pub(crate) struct Readiness {
+    waker: Mutex<Option<sys::Waker>>,
+    pending: AtomicUsize,
+    senders: AtomicUsize,
+    disconnected: AtomicBool,
+}
+
+impl Readiness {
+    /// Create a `Readiness` shared by a single sender to start with; clones
+    /// of that sender must call [`Readiness::inc_senders`].
+    pub(crate) fn new() -> Readiness {
+        Readiness {
+            waker: Mutex::new(None),
+            pending: AtomicUsize::new(0),
+            senders: AtomicUsize::new(1),
+            disconnected: AtomicBool::new(false),
+        }
+    }
+
+    pub(crate) fn register(
+        &self,
+        registry: &Registry,
+        token: Token,
+        _interests: Interest,
+    ) -> io::Result<()> {
+        let mut guard = self.waker.lock().unwrap();
+        if guard.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "readiness already registered",
+            ));
+        }
+
+        let waker = sys::Waker::new(registry.selector(), token)?;
+        // Messages may already be queued, or the channel may already be
+        // disconnected, from before we were registered, in which case the
+        // first poll must see us as readable right away.
+        if self.pending.load(Ordering::Acquire) > 0 || self.disconnected.load(Ordering::Acquire) {
+            waker.wake()?;
+        }
+        *guard = Some(waker);
+        Ok(())
+    }
+
+    pub(crate) fn reregister(
+        &self,
+        registry: &Registry,
+        token: Token,
+        _interests: Interest,
+    ) -> io::Result<()> {
+        // The underlying waker is tied to a single (registry, token) pair
+        // and can't be retargeted in place, so unlike `register`, this is
+        // expected to replace it with one for the new token.
+        let waker = sys::Waker::new(registry.selector(), token)?;
+        if self.pending.load(Ordering::Acquire) > 0 || self.disconnected.load(Ordering::Acquire) {
+            waker.wake()?;
+        }
+        *self.waker.lock().unwrap() = Some(waker);
+        Ok(())
+    }
+
+    pub(crate) fn deregister(&self) -> io::Result<()> {
+        *self.waker.lock().unwrap() = None;
+        Ok(())
+    }
+
+    /// Record that a value was sent, waking the receiver only on the
+    /// `0 -> 1` transition so a burst of sends costs a single wakeup.
+    pub(crate) fn notify(&self) {
+        if self.pending.fetch_add(1, Ordering::AcqRel) == 0 {
+            if let Some(waker) = self.waker.lock().unwrap().as_ref() {
+                let _ = waker.wake();
+            }
+        }
+    }
+
+    /// Record that `n` values were received.
+    ///
+    /// The selector is edge-triggered, so the readable edge that woke us up
+    /// for this drain is already consumed. If the channel still isn't empty
+    /// afterwards — whether because `n` was smaller than the queue (a
+    /// partial `recv_many`/`try_iter`) or because a sender raced us in — we
+    /// have to re-arm the waker ourselves or the rest of the queue is never
+    /// delivered; [`Waker`] can't be disarmed, so there's nothing to clear
+    /// when we do empty it out.
+    ///
+    /// [`Waker`]: crate::Waker
+    pub(crate) fn ack(&self, n: usize) {
+        if n == 0 {
+            return;
+        }
+
+        let prev = self.pending.fetch_sub(n, Ordering::AcqRel);
+        if prev > n {
+            if let Some(waker) = self.waker.lock().unwrap().as_ref() {
+                let _ = waker.wake();
+            }
+        }
+    }
+
+    /// Record that a single value was received by `try_recv`.
+    ///
+    /// Unlike [`Readiness::ack`], this never re-arms the waker, so draining
+    /// the channel one value at a time leaves no readable event behind.
+    /// Values still queued are not reported again until the next `0 -> 1`
+    /// transition in [`Readiness::notify`].
+    pub(crate) fn ack_one(&self) {
+        self.pending.fetch_sub(1, Ordering::AcqRel);
+    }
+
+    /// A sender was cloned; account for it so we know when the last one
+    /// goes away.
+    pub(crate) fn inc_senders(&self) {
+        self.senders.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// A sender was dropped. Returns `true` if it was the last one, in
+    /// which case the channel has already been marked disconnected.
+    pub(crate) fn dec_senders(&self) -> bool {
+        if self.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.disconnected.store(true, Ordering::Release);
+            if let Some(waker) = self.waker.lock().unwrap().as_ref() {
+                let _ = waker.wake();
+            }
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Writable readiness for a [`SyncSender`], mirroring [`Readiness`] but
+/// tracking free capacity instead of pending messages.
+///
+/// A bounded channel starts out writable (it's empty), and stops being
+/// writable once `occupied` reaches `bound`. [`SyncSender`] drains this into
+/// an `event::Source` registration so a full event loop can wait for
+/// [`Interest::WRITABLE`] instead of busy-looping on `try_send`.
+///
+/// Like [`Readiness`], this only ever calls `wake()`: when a full channel
+/// gains a free slot, and on registration if the channel has free capacity.
+/// [`Waker`] has no way to be cleared once armed, so there's no
+/// "un-writable" signal to send back.
+///
+/// [`SyncSender`]: crate::mpsc::SyncSender
+/// [`Interest::WRITABLE`]: crate::Interest::WRITABLE
+/// [`Waker`]: crate::Waker
+#[derive(Debug)]
+pub(crate) struct Capacity {
+    waker: Mutex<Option<sys::Waker>>,
+    /// The bound to compare `occupied` against for readiness purposes.
+    ///
+    /// A bound of `0` (a rendezvous channel) never actually has "free
+    /// capacity": `try_send` only succeeds when a receiver happens to be
+    /// waiting. Since we can't observe that, we treat it like a channel of
+    /// capacity 1 here rather than report "never writable", which would
+    /// otherwise be permanently wrong.
+    bound: usize,
+    occupied: AtomicUsize,
+}
+
+impl Capacity {
+    pub(crate) fn new(bound: usize) -> Capacity {
+        Capacity {
+            waker: Mutex::new(None),
+            bound: bound.max(1),
+            occupied: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn register(
+        &self,
+        registry: &Registry,
+        token: Token,
+        _interests: Interest,
+    ) -> io::Result<()> {
+        let mut guard = self.waker.lock().unwrap();
+        if guard.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "readiness already registered",
+            ));
+        }
+
+        let waker = sys::Waker::new(registry.selector(), token)?;
+        if self.occupied.load(Ordering::Acquire) < self.bound {
+            waker.wake()?;
+        }
+        *guard = Some(waker);
+        Ok(())
+    }
+
+    pub(crate) fn reregister(
+        &self,
+        registry: &Registry,
+        token: Token,
+        _interests: Interest,
+    ) -> io::Result<()> {
+        let waker = sys::Waker::new(registry.selector(), token)?;
+        if self.occupied.load(Ordering::Acquire) < self.bound {
+            waker.wake()?;
+        }
+        *self.waker.lock().unwrap() = Some(waker);
+        Ok(())
+    }
+
+    pub(crate) fn deregister(&self) -> io::Result<()> {
+        *self.waker.lock().unwrap() = None;
+        Ok(())
+    }
+
+    /// Record that a slot was filled. There's nothing to clear when the
+    /// channel becomes full; [`Waker`] can't be disarmed, so the sender
+    /// simply stops getting new wakeups until a slot frees up again.
+    pub(crate) fn reserve(&self) {
+        self.occupied.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Record that `n` slots were freed, waking the sender if the channel
+    /// was full before this release.
+    pub(crate) fn release_many(&self, n: usize) {
+        if n == 0 {
+            return;
+        }
+
+        let prev = self.occupied.fetch_sub(n, Ordering::AcqRel);
+        if prev >= self.bound && prev - n < self.bound {
+            if let Some(waker) = self.waker.lock().unwrap().as_ref() {
+                let _ = waker.wake();
+            }
+        }
+    }
+}